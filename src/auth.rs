@@ -0,0 +1,158 @@
+use rocket::{
+  http::Status,
+  request::{FromRequest, Outcome},
+  Request, State,
+};
+use sqlx::SqlitePool;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{Error, Result};
+
+/// An API key that has been checked against the `keys` table.
+pub struct ApiKey {
+  pub id: i64,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiKey {
+  type Error = Error;
+
+  async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+    let Some(key) = request.headers().get_one("x-api-key") else {
+      return Outcome::Error((
+        Status::Unauthorized,
+        Error(anyhow::anyhow!("missing x-api-key header")),
+      ));
+    };
+
+    let db = match request.guard::<&State<SqlitePool>>().await {
+      Outcome::Success(db) => db,
+      _ => {
+        return Outcome::Error((
+          Status::InternalServerError,
+          Error(anyhow::anyhow!("database not available")),
+        ))
+      },
+    };
+
+    let mut conn = match db.acquire().await {
+      Ok(conn) => conn,
+      Err(err) => return Outcome::Error((Status::InternalServerError, err.into())),
+    };
+
+    match sqlx::query!(r#"SELECT id FROM keys WHERE key = ?1"#, key)
+      .fetch_optional(&mut conn)
+      .await
+    {
+      Ok(Some(row)) => Outcome::Success(ApiKey { id: row.id }),
+      Ok(None) => Outcome::Error((Status::Unauthorized, Error(anyhow::anyhow!("invalid api key")))),
+      Err(err) => Outcome::Error((Status::InternalServerError, err.into())),
+    }
+  }
+}
+
+/// A per-key, per-group tumbling window budget: the current timestamp is truncated to a
+/// multiple of `window_secs` (not a rolling `now - window_secs`), so a burst straddling a
+/// boundary can admit up to ~2x `limit` across the two adjacent windows. `group`
+/// partitions the counter so an api key's `/vote` traffic doesn't eat into its
+/// `/resize_all` budget, or vice versa.
+pub struct RateLimit {
+  pub group: &'static str,
+  pub window_secs: i64,
+  pub limit: i64,
+}
+
+impl RateLimit {
+  pub const VOTE: RateLimit = RateLimit {
+    group: "vote",
+    window_secs: 60,
+    limit: 60,
+  };
+
+  pub const RESIZE_ALL: RateLimit = RateLimit {
+    group: "resize_all",
+    window_secs: 3600,
+    limit: 2,
+  };
+
+  /// Upserts this window's counter and reports whether the request is still within
+  /// budget. `time_window` is the current timestamp truncated to `window_secs` (a
+  /// tumbling window, not a sliding one), so old rows simply stop matching future
+  /// requests instead of needing cleanup — at the cost of allowing a burst that
+  /// straddles a window boundary to admit up to ~2x `limit` across the two windows.
+  async fn check(&self, db: &SqlitePool, api_key_id: i64) -> Result<bool> {
+    let mut conn = db.acquire().await?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    let time_window = now - (now % self.window_secs);
+
+    let row = sqlx::query!(
+      r#"
+INSERT INTO rate_limit (api_key_id, time_window, group_name, count)
+VALUES (?1, ?2, ?3, 1)
+ON CONFLICT(api_key_id, time_window, group_name)
+DO UPDATE SET count = count + 1
+RETURNING count
+      "#,
+      api_key_id,
+      time_window,
+      self.group,
+    )
+    .fetch_one(&mut conn)
+    .await?;
+
+    Ok(row.count <= self.limit)
+  }
+}
+
+async fn authenticate(request: &Request<'_>, rate_limit: &RateLimit) -> Outcome<i64, Error> {
+  let api_key = match ApiKey::from_request(request).await {
+    Outcome::Success(key) => key,
+    Outcome::Error(err) => return Outcome::Error(err),
+    Outcome::Forward(status) => return Outcome::Forward(status),
+  };
+
+  let db = match request.guard::<&State<SqlitePool>>().await {
+    Outcome::Success(db) => db,
+    _ => {
+      return Outcome::Error((
+        Status::InternalServerError,
+        Error(anyhow::anyhow!("database not available")),
+      ))
+    },
+  };
+
+  match rate_limit.check(db, api_key.id).await {
+    Ok(true) => Outcome::Success(api_key.id),
+    Ok(false) => Outcome::Error((
+      Status::TooManyRequests,
+      Error(anyhow::anyhow!("rate limit exceeded for {}", rate_limit.group)),
+    )),
+    Err(err) => Outcome::Error((Status::InternalServerError, err)),
+  }
+}
+
+/// Guards `/vote`: a valid `x-api-key` under the vote rate limit.
+pub struct VoteGuard(#[allow(dead_code)] pub i64);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for VoteGuard {
+  type Error = Error;
+
+  async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+    authenticate(request, &RateLimit::VOTE).await.map(VoteGuard)
+  }
+}
+
+/// Guards `/resize_all`: a valid `x-api-key` under the (much stricter) resize rate limit.
+pub struct ResizeAllGuard(#[allow(dead_code)] pub i64);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ResizeAllGuard {
+  type Error = Error;
+
+  async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+    authenticate(request, &RateLimit::RESIZE_ALL)
+      .await
+      .map(ResizeAllGuard)
+  }
+}
@@ -0,0 +1,149 @@
+use image::{DynamicImage, GenericImageView};
+
+const BASE83_CHARS: &[u8; 83] =
+  b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+/// Encodes `img` as a BlurHash string using `components_x` * `components_y` basis
+/// functions (the standard default is 4x3). The result is a short, URL-safe string
+/// the frontend can decode into an instant gradient placeholder before the full-size
+/// picture has loaded from `/files`.
+pub fn encode(img: &DynamicImage, components_x: u32, components_y: u32) -> String {
+  let rgba = img.to_rgba8();
+  let (width, height) = rgba.dimensions();
+
+  let mut factors = Vec::with_capacity((components_x * components_y) as usize);
+  for cy in 0..components_y {
+    for cx in 0..components_x {
+      factors.push(basis_factor(&rgba, width, height, cx, cy));
+    }
+  }
+
+  let dc = factors[0];
+  let ac = &factors[1..];
+
+  let max_ac = ac
+    .iter()
+    .flat_map(|c| c.iter().copied())
+    .fold(0f64, |max, v| max.max(v.abs()));
+
+  let quantized_max_ac = if ac.is_empty() {
+    0
+  } else {
+    (max_ac * 166.0 - 0.5).floor().clamp(0.0, 82.0) as u64
+  };
+  let max_ac_value = (quantized_max_ac as f64 + 1.0) / 166.0;
+
+  let size_flag = (components_x - 1) + (components_y - 1) * 9;
+
+  let mut result = String::with_capacity(6 + ac.len() * 2);
+  result.push_str(&encode_base83(size_flag as u64, 1));
+  result.push_str(&encode_base83(quantized_max_ac, 1));
+  result.push_str(&encode_base83(encode_dc(dc), 4));
+  for component in ac {
+    result.push_str(&encode_base83(encode_ac(*component, max_ac_value), 2));
+  }
+
+  result
+}
+
+/// `factor = sum(basis(x, y) * pixel_linear(x, y))` for one (cx, cy) basis function,
+/// normalized by the pixel count.
+fn basis_factor(rgba: &image::RgbaImage, width: u32, height: u32, cx: u32, cy: u32) -> [f64; 3] {
+  let normalization = if cx == 0 && cy == 0 { 1.0 } else { 2.0 };
+  let mut sum = [0f64; 3];
+
+  for y in 0..height {
+    for x in 0..width {
+      let basis = normalization
+        * (std::f64::consts::PI * cx as f64 * x as f64 / width as f64).cos()
+        * (std::f64::consts::PI * cy as f64 * y as f64 / height as f64).cos();
+
+      let pixel = rgba.get_pixel(x, y);
+      sum[0] += basis * srgb_to_linear(pixel[0]);
+      sum[1] += basis * srgb_to_linear(pixel[1]);
+      sum[2] += basis * srgb_to_linear(pixel[2]);
+    }
+  }
+
+  let scale = 1.0 / (width as f64 * height as f64);
+  [sum[0] * scale, sum[1] * scale, sum[2] * scale]
+}
+
+fn srgb_to_linear(value: u8) -> f64 {
+  let v = value as f64 / 255.0;
+  if v <= 0.04045 {
+    v / 12.92
+  } else {
+    ((v + 0.055) / 1.055).powf(2.4)
+  }
+}
+
+fn linear_to_srgb(value: f64) -> u8 {
+  let v = value.clamp(0.0, 1.0);
+  let srgb = if v <= 0.0031308 {
+    v * 12.92
+  } else {
+    1.055 * v.powf(1.0 / 2.4) - 0.055
+  };
+  (srgb * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+fn signed_pow(value: f64, exp: f64) -> f64 {
+  value.abs().powf(exp) * value.signum()
+}
+
+fn encode_dc(rgb: [f64; 3]) -> u64 {
+  let r = linear_to_srgb(rgb[0]) as u64;
+  let g = linear_to_srgb(rgb[1]) as u64;
+  let b = linear_to_srgb(rgb[2]) as u64;
+  (r << 16) | (g << 8) | b
+}
+
+fn encode_ac(rgb: [f64; 3], max_ac_value: f64) -> u64 {
+  let quantize = |v: f64| -> u64 {
+    (signed_pow(v / max_ac_value, 0.5) * 9.0 + 9.5)
+      .floor()
+      .clamp(0.0, 18.0) as u64
+  };
+
+  quantize(rgb[0]) * 19 * 19 + quantize(rgb[1]) * 19 + quantize(rgb[2])
+}
+
+fn encode_base83(mut value: u64, length: usize) -> String {
+  let mut digits = vec![0u8; length];
+  for digit in digits.iter_mut().rev() {
+    *digit = BASE83_CHARS[(value % 83) as usize];
+    value /= 83;
+  }
+
+  String::from_utf8(digits).expect("BASE83_CHARS is all ASCII")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use image::RgbImage;
+
+  #[test]
+  fn encode_flat_image_leaves_even_components_at_the_mid_point_and_recovers_the_dc_color() {
+    // On a flat-color image the sampled cosines sum to zero only for the even
+    // non-zero components (cx = 2 or cy = 2), which quantize to the mid-point ("fQ").
+    // The odd components keep a small residual, which gives a quantized max-AC of
+    // 5 ('5'). The 4x3 size flag ((4-1) + (3-1)*9 = 21) encodes to 'L'.
+    let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(32, 32, image::Rgb([120, 60, 200])));
+    let hash = encode(&img, 4, 3);
+
+    assert_eq!(hash.len(), 6 + 11 * 2);
+    assert!(hash.starts_with("L5"));
+    assert_eq!(&hash[6..], "$;fQ$;$;jvfQjvfQfQfQfQ");
+
+    let dc = (120u64 << 16) | (60 << 8) | 200;
+    assert_eq!(hash[2..6], encode_base83(dc, 4));
+  }
+
+  #[test]
+  fn encode_is_deterministic_for_the_same_input() {
+    let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(16, 12, image::Rgb([10, 200, 90])));
+    assert_eq!(encode(&img, 4, 3), encode(&img, 4, 3));
+  }
+}
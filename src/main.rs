@@ -1,5 +1,5 @@
 use console::style;
-use image::imageops::FilterType;
+use image::{imageops::FilterType, GenericImageView};
 use log::{error, info, warn};
 use rand::Rng;
 use rocket::{
@@ -11,11 +11,20 @@ use rocket::{
 };
 use rocket_cors::{AllowedHeaders, AllowedOrigins};
 use serde::{Deserialize, Serialize};
-use sqlx::SqlitePool;
-use std::{env, hash::Hasher, io::Write, path::Path, path::PathBuf, str::FromStr, time::Duration};
+use sqlx::{Row, SqlitePool};
+use std::{env, hash::Hasher, io::Write, path::Path, path::PathBuf, str::FromStr, sync::Arc};
 use tokio::{self, fs};
 use twox_hash::XxHash64;
 
+mod auth;
+mod blurhash;
+mod phash;
+mod store;
+mod tagging;
+mod watcher;
+
+use store::Store;
+
 #[derive(Debug)]
 pub struct Error(pub anyhow::Error);
 
@@ -36,15 +45,30 @@ impl<'r> Responder<'r, 'static> for Error {
 
 pub type Result<T = ()> = std::result::Result<T, Error>;
 
+#[derive(Debug, Clone, Serialize)]
+struct LabelInfo {
+  label: String,
+  confidence: f32,
+  model: String,
+}
+
 #[derive(Debug, Clone, Serialize)]
 struct ImageEntry {
   id: String,
   hash: String,
+  blur_hash: String,
+  width: i64,
+  height: i64,
+  labels: Vec<LabelInfo>,
 }
 
-#[rocket::get("/")]
-async fn index(db: &State<SqlitePool>) -> Result<(Status, Value)> {
-  let next = get_next_pic(db).await?;
+#[rocket::get("/?<label>&<exclude>")]
+async fn index(
+  db: &State<SqlitePool>,
+  label: Option<String>,
+  exclude: Option<String>,
+) -> Result<(Status, Value)> {
+  let next = get_next_pic(db, label.as_deref(), exclude.as_deref()).await?;
   if next.is_none() {
     return Ok((
       Status::InternalServerError,
@@ -73,39 +97,88 @@ fn biased_random(max: i32) -> i32 {
   (random * max as f32) as i32
 }
 
-async fn get_next_pic(db: &SqlitePool) -> Result<Option<ImageEntry>> {
-  let mut db = db.acquire().await?;
-  let count = sqlx::query!("SELECT COUNT(*) AS count FROM images WHERE sorting >= -3")
-    .fetch_one(&mut db)
-    .await?;
+/// `label`/`exclude` restrict the biased-random sample to images carrying (or
+/// lacking) a given ML-assigned tag. Since the filter is optional in either
+/// direction, the query is built at runtime rather than with `sqlx::query!`.
+async fn get_next_pic(
+  db: &SqlitePool,
+  label: Option<&str>,
+  exclude: Option<&str>,
+) -> Result<Option<ImageEntry>> {
+  let mut conn = db.acquire().await?;
 
-  let skip = biased_random(count.count);
-  let result = sqlx::query!(
-    r#"
-SELECT id, hash
-FROM images
-WHERE sorting >= -3
-ORDER BY confidence ASC
-LIMIT 1
-OFFSET ?1
-    "#,
-    skip
-  )
-  .fetch_optional(&mut db)
-  .await?;
+  let mut where_clause = String::from("WHERE sorting >= -3");
+  if label.is_some() {
+    where_clause.push_str(" AND id IN (SELECT image_id FROM file_labels WHERE label = ?)");
+  }
+  if exclude.is_some() {
+    where_clause.push_str(" AND id NOT IN (SELECT image_id FROM file_labels WHERE label = ?)");
+  }
 
-  if result.is_none() {
-    return Ok(None);
+  let mut count_query = sqlx::query_scalar::<_, i64>(&format!(
+    "SELECT COUNT(*) FROM images {where_clause}"
+  ));
+  if let Some(label) = label {
+    count_query = count_query.bind(label);
+  }
+  if let Some(exclude) = exclude {
+    count_query = count_query.bind(exclude);
   }
+  let count = count_query.fetch_one(&mut *conn).await?;
 
-  let result = result.unwrap();
+  let skip = biased_random(count as i32);
+
+  let mut select_query = sqlx::query(&format!(
+    "SELECT id, hash, blur_hash, width, height FROM images {where_clause} ORDER BY confidence ASC LIMIT 1 OFFSET ?"
+  ));
+  if let Some(label) = label {
+    select_query = select_query.bind(label);
+  }
+  if let Some(exclude) = exclude {
+    select_query = select_query.bind(exclude);
+  }
+  select_query = select_query.bind(skip);
+
+  let Some(row) = select_query.fetch_optional(&mut *conn).await? else {
+    return Ok(None);
+  };
+
+  let id: String = row.try_get("id")?;
+  let labels = fetch_labels(&mut conn, &id).await?;
 
   Ok(Some(ImageEntry {
-    id: result.id.unwrap(),
-    hash: result.hash.unwrap(),
+    id,
+    hash: row.try_get("hash")?,
+    blur_hash: row.try_get::<Option<String>, _>("blur_hash")?.unwrap_or_default(),
+    width: row.try_get::<Option<i64>, _>("width")?.unwrap_or_default(),
+    height: row.try_get::<Option<i64>, _>("height")?.unwrap_or_default(),
+    labels,
   }))
 }
 
+async fn fetch_labels(
+  conn: &mut sqlx::pool::PoolConnection<sqlx::Sqlite>,
+  image_id: &str,
+) -> Result<Vec<LabelInfo>> {
+  let rows = sqlx::query!(
+    r#"SELECT label, confidence, model FROM file_labels WHERE image_id = ?1 ORDER BY confidence DESC"#,
+    image_id
+  )
+  .fetch_all(conn)
+  .await?;
+
+  Ok(
+    rows
+      .into_iter()
+      .map(|row| LabelInfo {
+        label: row.label,
+        confidence: row.confidence,
+        model: row.model,
+      })
+      .collect(),
+  )
+}
+
 #[derive(Debug, Clone, Deserialize)]
 struct VoteRequest {
   id: String,
@@ -113,7 +186,11 @@ struct VoteRequest {
 }
 
 #[rocket::post("/vote", data = "<req>")]
-async fn vote(db: &State<SqlitePool>, req: Json<VoteRequest>) -> Result<(Status, Value)> {
+async fn vote(
+  db: &State<SqlitePool>,
+  _auth: auth::VoteGuard,
+  req: Json<VoteRequest>,
+) -> Result<(Status, Value)> {
   let mut db = db.acquire().await?;
   let record = sqlx::query!(
     r#"
@@ -193,11 +270,13 @@ fn calc_sort_value(ups: i64, downs: i64) -> f32 {
     / (1.0 + z * z / n)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Config {
-  raws_path: PathBuf,
-  imports_path: PathBuf,
-  resized_path: PathBuf,
+  pub(crate) imports_path: PathBuf,
+  pub(crate) phash_threshold: u32,
+  pub(crate) raw_store: Arc<dyn Store>,
+  pub(crate) resized_store: Arc<dyn Store>,
+  pub(crate) tagger: Option<Arc<tagging::Tagger>>,
 }
 
 #[tokio::main]
@@ -224,21 +303,30 @@ async fn main() -> Result<()> {
   let pool = sqlx::SqlitePool::connect(&db_path).await?;
   sqlx::migrate!().run(&pool).await?;
 
-  let imports_path =
-    env::var("VOTER_IMPORTS_DIR").unwrap_or_else(|_| "./storage/imports".to_string());
-  let raws_path = env::var("VOTER_RAWS_DIR").unwrap_or_else(|_| "./storage/raws".to_string());
-  let resized_path =
-    env::var("VOTER_RESIZED_DIR").unwrap_or_else(|_| "./storage/resized".to_string());
+  let imports_path: PathBuf =
+    env::var("VOTER_IMPORTS_DIR").unwrap_or_else(|_| "./storage/imports".to_string()).into();
+  let raws_path: PathBuf =
+    env::var("VOTER_RAWS_DIR").unwrap_or_else(|_| "./storage/raws".to_string()).into();
+  let resized_path: PathBuf =
+    env::var("VOTER_RESIZED_DIR").unwrap_or_else(|_| "./storage/resized".to_string()).into();
+  let using_filesystem_store = env::var("VOTER_STORE_BACKEND").as_deref() != Ok("s3");
+
+  let (raw_store, resized_store) = store::build_stores(&raws_path, &resized_path).await?;
+  let tagger = build_tagger_from_env()?;
 
   let config = Config {
-    raws_path: raws_path.into(),
-    imports_path: imports_path.into(),
-    resized_path: resized_path.clone().into(),
+    imports_path: imports_path.clone(),
+    phash_threshold: phash_threshold_from_env(),
+    raw_store,
+    resized_store,
+    tagger,
   };
 
   fs::create_dir_all(&config.imports_path).await?;
-  fs::create_dir_all(&config.raws_path).await?;
-  fs::create_dir_all(&config.resized_path).await?;
+  if using_filesystem_store {
+    fs::create_dir_all(&raws_path).await?;
+    fs::create_dir_all(&resized_path).await?;
+  }
 
   let cors = rocket_cors::CorsOptions {
     allowed_origins: AllowedOrigins::all(),
@@ -255,57 +343,58 @@ async fn main() -> Result<()> {
   let r = rocket::build()
     .manage(config.clone())
     .manage(pool.clone())
-    .mount("/", rocket::routes![index, vote, resize_all_images])
-    .mount("/files", FileServer::from(&resized_path))
-    .attach(cors)
-    .ignite()
-    .await?;
+    .mount("/", rocket::routes![index, vote, resize_all_images]);
 
-  tokio::spawn(async move {
-    loop {
-      info!("Checking for new imports...");
-      match check_imports(&config, &pool).await {
-        Ok(_) => info!("Imports check complete"),
-        Err(err) => error!("Failed to check imports: {err:?}"),
-      };
+  // When the resized derivatives live on the local volume, Rocket can serve them
+  // directly; otherwise proxy-redirect each request to the object store's URL.
+  let r = if using_filesystem_store {
+    r.mount("/files", FileServer::from(&resized_path))
+  } else {
+    r.mount("/files", rocket::routes![files_redirect])
+  };
 
-      tokio::time::sleep(Duration::from_secs(5)).await;
-    }
-  });
+  let r = r.attach(cors).ignite().await?;
+
+  watcher::spawn_import_watcher(config, pool);
 
   let _ = r.launch().await?;
   Ok(())
 }
 
+#[rocket::get("/<key..>")]
+async fn files_redirect(
+  config: &State<Config>,
+  key: PathBuf,
+) -> Result<rocket::response::Redirect> {
+  let key = key.to_string_lossy().to_string();
+  let url = config.resized_store.url_for(&key).await?;
+  Ok(rocket::response::Redirect::to(url))
+}
+
 #[rocket::post("/resize_all")]
-async fn resize_all_images(db: &State<SqlitePool>) -> Result<(Status, Value)> {
+async fn resize_all_images(
+  db: &State<SqlitePool>,
+  config: &State<Config>,
+  _auth: auth::ResizeAllGuard,
+) -> Result<(Status, Value)> {
   let mut conn = db.acquire().await?;
 
   info!("Resizing all Images!");
-  let imports_path =
-    env::var("VOTER_IMPORTS_DIR").unwrap_or_else(|_| "./storage/imports".to_string());
-  let raws_path = env::var("VOTER_RAWS_DIR").unwrap_or_else(|_| "./storage/raws".to_string());
-  let resized_path =
-    env::var("VOTER_RESIZED_DIR").unwrap_or_else(|_| "./storage/resized".to_string());
-
-  let config = Config {
-    raws_path: raws_path.into(),
-    imports_path: imports_path.into(),
-    resized_path: resized_path.clone().into(),
-  };
   let records = sqlx::query!(r#"SELECT id, filename, hash FROM images"#,)
-    .fetch_optional(&mut conn)
+    .fetch_all(&mut conn)
     .await?;
 
   for record in records {
-    let raw_image_path = config.raws_path.join(record.hash.to_string() + ".png");
-    info!("Resizing image: {}", &raw_image_path.clone().display());
-    resize_img(
-      &config,
-      &raw_image_path,
-      &record.hash.parse::<u64>().unwrap(),
-    )
-    .await?;
+    let filename = record.filename.unwrap_or_default();
+    let ext = Path::new(&filename)
+      .extension()
+      .map(|ext| ext.to_string_lossy().to_lowercase())
+      .unwrap_or_else(|| "jpg".to_string());
+    let raw_key = format!("{}.{ext}", record.hash);
+    info!("Resizing image: {raw_key}");
+    let data = config.raw_store.load(&raw_key).await?;
+    let decoded = image::load_from_memory(&data)?;
+    resize_img(config, &decoded, &record.hash.parse::<u64>().unwrap()).await?;
   }
   info!("Resizing Done");
   Ok((Status::Ok, json!({ "success": true })))
@@ -315,11 +404,33 @@ async fn save_image(
   config: &Config,
   db: &SqlitePool,
   original_image_path: &Path,
-  raw_image_path: &Path,
+  ext: &str,
   hash: u64,
+  data: Vec<u8>,
 ) -> Result<()> {
-  fs::copy(&original_image_path, &raw_image_path).await?;
-  resize_img(&config, &raw_image_path, &hash).await?;
+  let decoded = image::load_from_memory(&data)?;
+  let image_phash = phash::compute_phash(&decoded) as i64;
+  let raw_key = format!("{hash}.{ext}");
+
+  if let Some(existing_id) = find_near_duplicate(db, image_phash, config.phash_threshold).await? {
+    info!(
+      "Skipping {original_image_path:?}, near-duplicate of existing image {existing_id} (phash threshold {})",
+      config.phash_threshold
+    );
+    // Still persist the raw bytes under the content-hash key so the `raw_store.exists`
+    // gate in `process_import` trips on the next sweep; otherwise an unchanged file
+    // that's never actually imported gets re-decoded and re-scanned against the whole
+    // `images` table forever.
+    config.raw_store.save(&raw_key, data.into()).await?;
+    return Ok(());
+  }
+
+  config.raw_store.save(&raw_key, data.into()).await?;
+  resize_img(config, &decoded, &hash).await?;
+
+  let (width, height) = decoded.dimensions();
+  let thumbnail = decoded.resize(100, 100, FilterType::Triangle);
+  let blur_hash = blurhash::encode(&thumbnail, 4, 3);
 
   let image_id = ulid::Ulid::new().to_string();
   let hash_str = hash.to_string();
@@ -332,29 +443,135 @@ async fn save_image(
 
   let mut conn = db.acquire().await?;
   sqlx::query!(
-    r#"INSERT INTO images ( id, filename, hash ) VALUES ( ?1, ?2, ?3 )"#,
+    r#"INSERT INTO images ( id, filename, hash, phash, blur_hash, width, height )
+VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7 )"#,
     image_id,
     original_filename,
     hash_str,
+    image_phash,
+    blur_hash,
+    width,
+    height,
   )
   .execute(&mut conn)
   .await?;
 
+  if let Some(tagger) = &config.tagger {
+    let model_name = tagger.model_name.clone();
+    let tagger = Arc::clone(tagger);
+    let decoded_for_tagging = decoded.clone();
+    // `classify` runs CPU-bound ONNX inference; keep it off the async reactor so a
+    // bulk import doesn't stall every other request being served on this worker. A
+    // tagging failure (e.g. a model the runtime can't run) shouldn't fail the whole
+    // import, so log it and persist the image without labels instead of propagating.
+    let labels = match tokio::task::spawn_blocking(move || tagger.classify(&decoded_for_tagging)).await {
+      Ok(Ok(labels)) => labels,
+      Ok(Err(err)) => {
+        warn!("Tagging failed for {image_id} ({original_image_path:?}), importing without labels: {err:?}");
+        Vec::new()
+      },
+      Err(err) => {
+        warn!("Tagging task panicked for {image_id} ({original_image_path:?}), importing without labels: {err:?}");
+        Vec::new()
+      },
+    };
+
+    for (label, confidence) in labels {
+      sqlx::query!(
+        r#"INSERT INTO file_labels ( image_id, label, confidence, model ) VALUES ( ?1, ?2, ?3, ?4 )"#,
+        image_id,
+        label,
+        confidence,
+        model_name,
+      )
+      .execute(&mut conn)
+      .await?;
+    }
+  }
+
   Ok(())
 }
 
-async fn resize_img(config: &Config, raw_image_path: &Path, hash: &u64) -> Result<()> {
+/// Finds an already-imported image whose perceptual hash differs from `candidate_phash`
+/// in fewer than `threshold` bits, if any. Visually near-identical re-encodes/resizes end up
+/// with a small Hamming distance even though their raw-byte hashes differ entirely.
+async fn find_near_duplicate(
+  db: &SqlitePool,
+  candidate_phash: i64,
+  threshold: u32,
+) -> Result<Option<String>> {
+  let mut conn = db.acquire().await?;
+  let existing = sqlx::query!(r#"SELECT id, phash FROM images WHERE phash IS NOT NULL"#)
+    .fetch_all(&mut conn)
+    .await?;
+
+  for row in existing {
+    let Some(existing_phash) = row.phash else {
+      continue;
+    };
+
+    if phash::hamming_distance(candidate_phash as u64, existing_phash as u64) < threshold {
+      return Ok(Some(row.id.unwrap_or_default()));
+    }
+  }
+
+  Ok(None)
+}
+
+/// Default Hamming distance (out of 64 bits) below which two images are treated as
+/// near-duplicates; overridable via `VOTER_PHASH_THRESHOLD`.
+const DEFAULT_PHASH_THRESHOLD: u32 = 10;
+
+fn phash_threshold_from_env() -> u32 {
+  env::var("VOTER_PHASH_THRESHOLD")
+    .ok()
+    .and_then(|v| v.parse().ok())
+    .unwrap_or(DEFAULT_PHASH_THRESHOLD)
+}
+
+/// Number of labels persisted per imported image when a tagging model is configured.
+const TAGGING_TOP_N: usize = 5;
+
+/// Loads the optional ONNX tagging model from `VOTER_MODEL_PATH`/`VOTER_LABELS_PATH`.
+/// Tagging is skipped entirely (not an error) when either is unset, so operators who
+/// don't need content labels aren't forced to ship a model.
+fn build_tagger_from_env() -> Result<Option<Arc<tagging::Tagger>>> {
+  let (Ok(model_path), Ok(labels_path)) = (
+    env::var("VOTER_MODEL_PATH"),
+    env::var("VOTER_LABELS_PATH"),
+  ) else {
+    info!("VOTER_MODEL_PATH/VOTER_LABELS_PATH not set, ML tagging disabled");
+    return Ok(None);
+  };
+
+  let model_name = env::var("VOTER_MODEL_NAME").unwrap_or_else(|_| "default".to_string());
+  let tagger = tagging::Tagger::load(
+    Path::new(&model_path),
+    Path::new(&labels_path),
+    model_name,
+    TAGGING_TOP_N,
+  )?;
+
+  Ok(Some(Arc::new(tagger)))
+}
+
+async fn resize_img(config: &Config, decoded: &image::DynamicImage, hash: &u64) -> Result<()> {
   let resized_filename = format!("{hash}.jpg");
-  let resized_path = config.resized_path.join(&resized_filename);
-  let img = image::open(&raw_image_path)?;
+  let resized_img = decoded.resize(1080, 1080, FilterType::Lanczos3);
+  let resized_bytes = store::encode_jpeg(&resized_img)?;
 
-  let resized_img = img.resize(1080, 1080, FilterType::Lanczos3);
-  resized_img.save(&resized_path)?;
+  config
+    .resized_store
+    .save(&resized_filename, resized_bytes)
+    .await?;
 
   Ok(())
 }
 
-async fn check_imports(config: &Config, db: &SqlitePool) -> Result<()> {
+/// Walks the whole `imports_path` tree and imports every picture found. Used for the
+/// one-shot startup scan and the periodic backstop sweep; single files discovered by
+/// the filesystem watcher go through [`process_import`] instead.
+pub(crate) async fn check_imports(config: &Config, db: &SqlitePool) -> Result<()> {
   let mut walk_dirs = vec![config.imports_path.clone()];
   while !walk_dirs.is_empty() {
     let dir = walk_dirs.pop().unwrap();
@@ -371,40 +588,51 @@ async fn check_imports(config: &Config, db: &SqlitePool) -> Result<()> {
         continue;
       }
 
-      let ext = match path.extension() {
-        Some(ext) => ext,
-        None => {
-          warn!("No extension found for file {file:?}");
-          continue;
-        }
-      };
+      process_import(config, db, &path).await?;
+    }
+  }
 
-      let ext = ext.to_string_lossy().to_string().to_lowercase();
-      match ext.as_str() {
-        "jpg" | "jpeg" => {},
-        other => {
-          warn!("skipping extension {other}");
-          continue;
-        },
-      };
+  Ok(())
+}
 
-      let data = fs::read(file.path()).await?;
-      let mut hasher = XxHash64::with_seed(0);
-      hasher.write(&data);
+/// Imports a single candidate file if it's a picture we don't already have. Safe to
+/// call repeatedly for the same path (e.g. multiple debounced write events).
+pub(crate) async fn process_import(config: &Config, db: &SqlitePool, path: &Path) -> Result<()> {
+  if !path.is_file() {
+    return Ok(());
+  }
 
-      let file_hash = hasher.finish();
-      info!("Found new file: {file:?} with hash {file_hash}");
+  let ext = match path.extension() {
+    Some(ext) => ext,
+    None => {
+      warn!("No extension found for file {path:?}");
+      return Ok(());
+    }
+  };
 
-      let new_file_name = format!("{file_hash}.{ext}");
-      let raw_image_path = config.raws_path.to_owned().join(&new_file_name);
-      if raw_image_path.exists() {
-        info!("File {raw_image_path:?} was already imported ({file:?}), skipping");
-        continue;
-      }
+  let ext = ext.to_string_lossy().to_string().to_lowercase();
+  match ext.as_str() {
+    "jpg" | "jpeg" => {},
+    other => {
+      warn!("skipping extension {other}");
+      return Ok(());
+    },
+  };
 
-      save_image(config, db, &path, &raw_image_path, file_hash).await?;
-    }
+  let data = fs::read(path).await?;
+  let mut hasher = XxHash64::with_seed(0);
+  hasher.write(&data);
+
+  let file_hash = hasher.finish();
+  info!("Found new file: {path:?} with hash {file_hash}");
+
+  let raw_key = format!("{file_hash}.{ext}");
+  if config.raw_store.exists(&raw_key).await? {
+    info!("File {raw_key} was already imported ({path:?}), skipping");
+    return Ok(());
   }
 
+  save_image(config, db, path, &ext, file_hash, data).await?;
+
   Ok(())
 }
@@ -0,0 +1,69 @@
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+
+/// Width/height of the downscaled grayscale image used to derive the hash: 9 columns
+/// lets each row yield 8 left/right comparisons, for 8 rows * 8 bits = 64 bits total.
+const HASH_WIDTH: u32 = 9;
+const HASH_HEIGHT: u32 = 8;
+
+/// Computes a dHash-style perceptual hash: downscale to grayscale, then for each row
+/// set a bit when a pixel is brighter than its right neighbour. Near-identical images
+/// (recompressed, resized, lightly cropped) produce hashes a small Hamming distance
+/// apart, unlike the raw-byte `XxHash64` already used for exact-duplicate detection.
+pub fn compute_phash(img: &DynamicImage) -> u64 {
+  let small = img
+    .grayscale()
+    .resize_exact(HASH_WIDTH, HASH_HEIGHT, FilterType::Triangle);
+
+  let mut hash = 0u64;
+  let mut bit = 0;
+  for y in 0..HASH_HEIGHT {
+    for x in 0..HASH_WIDTH - 1 {
+      let left = small.get_pixel(x, y)[0];
+      let right = small.get_pixel(x + 1, y)[0];
+      if left > right {
+        hash |= 1 << bit;
+      }
+      bit += 1;
+    }
+  }
+
+  hash
+}
+
+/// Number of differing bits between two hashes, used to judge how visually similar
+/// two images are: 0 is identical, and anything under the configured threshold is
+/// treated as a near-duplicate.
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+  (a ^ b).count_ones()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use image::{GrayImage, Luma};
+
+  #[test]
+  fn hamming_distance_is_symmetric_and_counts_bit_differences() {
+    assert_eq!(hamming_distance(0, 0), 0);
+    assert_eq!(hamming_distance(0, u64::MAX), 64);
+    assert_eq!(hamming_distance(0b1010, 0b0101), 4);
+    assert_eq!(hamming_distance(0b1010, 0b0101), hamming_distance(0b0101, 0b1010));
+  }
+
+  #[test]
+  fn compute_phash_sets_a_bit_per_descending_row_step() {
+    // 9x8 so `resize_exact` is a no-op, and every row strictly decreases left to
+    // right, so every left/right comparison is `true`: the hash should be all ones.
+    let img = GrayImage::from_fn(HASH_WIDTH, HASH_HEIGHT, |x, _y| Luma([(80 - 10 * x as i32) as u8]));
+    let hash = compute_phash(&DynamicImage::ImageLuma8(img));
+    assert_eq!(hash, u64::MAX);
+  }
+
+  #[test]
+  fn compute_phash_is_identical_for_identical_images() {
+    let img = GrayImage::from_fn(HASH_WIDTH, HASH_HEIGHT, |x, y| Luma([((x + y) * 17) as u8]));
+    let a = compute_phash(&DynamicImage::ImageLuma8(img.clone()));
+    let b = compute_phash(&DynamicImage::ImageLuma8(img));
+    assert_eq!(hamming_distance(a, b), 0);
+  }
+}
@@ -0,0 +1,150 @@
+use async_trait::async_trait;
+use aws_config::BehaviorVersion;
+use bytes::Bytes;
+use std::{env, io::Cursor, path::{Path, PathBuf}, sync::Arc};
+
+use crate::Result;
+
+/// Abstracts where raw and resized picture bytes actually live, so the rest of the
+/// app doesn't care whether it's talking to a local volume or object storage.
+#[async_trait]
+pub trait Store: Send + Sync {
+  async fn save(&self, key: &str, bytes: Bytes) -> Result<()>;
+  async fn load(&self, key: &str) -> Result<Bytes>;
+  async fn url_for(&self, key: &str) -> Result<String>;
+  async fn exists(&self, key: &str) -> Result<bool>;
+}
+
+/// Selects the storage backend from `VOTER_STORE_BACKEND` (`filesystem`, the
+/// default, or `s3`) and builds the pair of stores used for raw originals and
+/// resized derivatives.
+pub async fn build_stores(
+  raws_path: &Path,
+  resized_path: &Path,
+) -> Result<(Arc<dyn Store>, Arc<dyn Store>)> {
+  match env::var("VOTER_STORE_BACKEND").as_deref() {
+    Ok("s3") => {
+      let bucket = env::var("VOTER_S3_BUCKET")
+        .map_err(|_| anyhow::anyhow!("VOTER_S3_BUCKET must be set when VOTER_STORE_BACKEND=s3"))?;
+
+      let raw_store = S3Store::new(bucket.clone(), "raws/".to_string()).await?;
+      let resized_store = S3Store::new(bucket, "resized/".to_string()).await?;
+      Ok((Arc::new(raw_store), Arc::new(resized_store)))
+    },
+    _ => Ok((
+      Arc::new(FilesystemStore::new(raws_path.to_path_buf())),
+      Arc::new(FilesystemStore::new(resized_path.to_path_buf())),
+    )),
+  }
+}
+
+/// Current behavior: everything lives under a directory on the local volume, served
+/// back out by Rocket's `FileServer` for the resized derivatives.
+pub struct FilesystemStore {
+  base_dir: PathBuf,
+}
+
+impl FilesystemStore {
+  pub fn new(base_dir: PathBuf) -> Self {
+    Self { base_dir }
+  }
+}
+
+#[async_trait]
+impl Store for FilesystemStore {
+  async fn save(&self, key: &str, bytes: Bytes) -> Result<()> {
+    tokio::fs::write(self.base_dir.join(key), &bytes).await?;
+    Ok(())
+  }
+
+  async fn load(&self, key: &str) -> Result<Bytes> {
+    let data = tokio::fs::read(self.base_dir.join(key)).await?;
+    Ok(Bytes::from(data))
+  }
+
+  async fn url_for(&self, key: &str) -> Result<String> {
+    Ok(format!("/files/{key}"))
+  }
+
+  async fn exists(&self, key: &str) -> Result<bool> {
+    Ok(self.base_dir.join(key).exists())
+  }
+}
+
+/// Object-storage backend so the service can run on ephemeral/containerized hosts
+/// without a persistent volume. Selected via `VOTER_STORE_BACKEND=s3`.
+pub struct S3Store {
+  client: aws_sdk_s3::Client,
+  bucket: String,
+  prefix: String,
+}
+
+impl S3Store {
+  pub async fn new(bucket: String, prefix: String) -> Result<Self> {
+    let shared_config = aws_config::load_defaults(BehaviorVersion::latest()).await;
+    let client = aws_sdk_s3::Client::new(&shared_config);
+    Ok(Self { client, bucket, prefix })
+  }
+
+  fn object_key(&self, key: &str) -> String {
+    format!("{}{key}", self.prefix)
+  }
+}
+
+#[async_trait]
+impl Store for S3Store {
+  async fn save(&self, key: &str, bytes: Bytes) -> Result<()> {
+    self
+      .client
+      .put_object()
+      .bucket(&self.bucket)
+      .key(self.object_key(key))
+      .body(bytes.into())
+      .send()
+      .await?;
+
+    Ok(())
+  }
+
+  async fn load(&self, key: &str) -> Result<Bytes> {
+    let object = self
+      .client
+      .get_object()
+      .bucket(&self.bucket)
+      .key(self.object_key(key))
+      .send()
+      .await?;
+
+    Ok(object.body.collect().await?.into_bytes())
+  }
+
+  async fn url_for(&self, key: &str) -> Result<String> {
+    Ok(format!(
+      "https://{}.s3.amazonaws.com/{}",
+      self.bucket,
+      self.object_key(key)
+    ))
+  }
+
+  async fn exists(&self, key: &str) -> Result<bool> {
+    match self
+      .client
+      .head_object()
+      .bucket(&self.bucket)
+      .key(self.object_key(key))
+      .send()
+      .await
+    {
+      Ok(_) => Ok(true),
+      Err(err) if err.as_service_error().is_some_and(|e| e.is_not_found()) => Ok(false),
+      Err(err) => Err(err.into()),
+    }
+  }
+}
+
+/// Encodes an image to JPEG bytes in memory, ready to hand to a [`Store`].
+pub fn encode_jpeg(img: &image::DynamicImage) -> Result<Bytes> {
+  let mut buffer = Cursor::new(Vec::new());
+  img.write_to(&mut buffer, image::ImageFormat::Jpeg)?;
+  Ok(Bytes::from(buffer.into_inner()))
+}
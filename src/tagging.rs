@@ -0,0 +1,70 @@
+use image::{imageops::FilterType, DynamicImage};
+use ort::{inputs, session::Session};
+use std::path::Path;
+
+use crate::Result;
+
+/// Input resolution most ONNX image-classification backbones (e.g. ResNet/MobileNet
+/// trained on ImageNet) expect.
+const INPUT_SIZE: u32 = 224;
+
+/// Runs imported pictures through an ONNX classifier to produce content labels so the
+/// voting stream can be curated/filtered (see `?label=`/`?exclude=` on `get_next_pic`).
+pub struct Tagger {
+  session: Session,
+  labels: Vec<String>,
+  pub model_name: String,
+  top_n: usize,
+}
+
+impl Tagger {
+  pub fn load(model_path: &Path, labels_path: &Path, model_name: String, top_n: usize) -> Result<Self> {
+    let session = Session::builder()?.commit_from_file(model_path)?;
+    let labels = std::fs::read_to_string(labels_path)?
+      .lines()
+      .map(|line| line.to_string())
+      .collect();
+
+    Ok(Self {
+      session,
+      labels,
+      model_name,
+      top_n,
+    })
+  }
+
+  /// Classifies `img`, returning the top-N `(label, confidence)` pairs by descending
+  /// confidence.
+  pub fn classify(&self, img: &DynamicImage) -> Result<Vec<(String, f32)>> {
+    let resized = img
+      .resize_exact(INPUT_SIZE, INPUT_SIZE, FilterType::Triangle)
+      .to_rgb8();
+
+    let mut input = ndarray::Array4::<f32>::zeros((1, 3, INPUT_SIZE as usize, INPUT_SIZE as usize));
+    for (x, y, pixel) in resized.enumerate_pixels() {
+      for channel in 0..3 {
+        input[[0, channel, y as usize, x as usize]] = pixel[channel] as f32 / 255.0;
+      }
+    }
+
+    // Don't assume the input tensor is named "input": common ImageNet backbones use
+    // "data"/"data_0"/"input.1" depending on the exporter, so ask the session instead.
+    let input_name = self.session.inputs[0].name.as_str();
+    let outputs = self.session.run(inputs![input_name => input.view()]?)?;
+    let (_, logits) = outputs[0].try_extract_raw_tensor::<f32>()?;
+    let probabilities = softmax(logits);
+
+    let mut scored: Vec<(String, f32)> = self.labels.iter().cloned().zip(probabilities).collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.truncate(self.top_n);
+
+    Ok(scored)
+  }
+}
+
+fn softmax(logits: &[f32]) -> Vec<f32> {
+  let max = logits.iter().cloned().fold(f32::MIN, f32::max);
+  let exps: Vec<f32> = logits.iter().map(|logit| (logit - max).exp()).collect();
+  let sum: f32 = exps.iter().sum();
+  exps.into_iter().map(|v| v / sum).collect()
+}
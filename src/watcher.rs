@@ -0,0 +1,111 @@
+use log::{error, info, warn};
+use notify::{
+  event::{CreateKind, ModifyKind},
+  Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use sqlx::SqlitePool;
+use std::{
+  collections::HashSet,
+  path::PathBuf,
+  time::Duration,
+};
+use tokio::sync::mpsc;
+
+use crate::{check_imports, process_import, Config, Result};
+
+/// How long to wait after the last observed event before acting on a batch,
+/// so editors/downloads that write a file in several chunks only trigger one pass.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Backstop sweep in case the OS watch queue drops events (e.g. `inotify` overflow).
+const SLOW_SWEEP_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Watches `config.imports_path` for new/modified files and imports them as they land,
+/// instead of re-scanning the whole tree on a fixed timer.
+pub fn spawn_import_watcher(config: Config, pool: SqlitePool) {
+  tokio::spawn(async move {
+    info!("Running initial import scan...");
+    match check_imports(&config, &pool).await {
+      Ok(_) => info!("Initial import scan complete"),
+      Err(err) => error!("Initial import scan failed: {err:?}"),
+    }
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<PathBuf>();
+    // Keep a sender alive for the lifetime of this task regardless of whether the
+    // watcher itself started: if `start_watcher` fails, the only other sender (moved
+    // into its closure) is never created, and `rx.recv()` would otherwise resolve to
+    // `None` on the first iteration, breaking the loop and killing the sweep fallback.
+    let _tx_guard = tx.clone();
+    let watcher = match start_watcher(&config, tx) {
+      Ok(watcher) => Some(watcher),
+      Err(err) => {
+        error!("Failed to start filesystem watcher, falling back to periodic sweeps only: {err:?}");
+        None
+      },
+    };
+
+    let mut sweep = tokio::time::interval(SLOW_SWEEP_INTERVAL);
+    sweep.tick().await; // first tick fires immediately; we already scanned above
+
+    let mut pending: HashSet<PathBuf> = HashSet::new();
+    loop {
+      tokio::select! {
+        path = rx.recv() => {
+          match path {
+            Some(path) => {
+              pending.insert(path);
+              // Drain whatever else arrived while we were waiting so a burst of
+              // writes collapses into a single debounce window.
+              while let Ok(path) = rx.try_recv() {
+                pending.insert(path);
+              }
+            },
+            None => break,
+          }
+
+          // Keep collecting until no new event has arrived for a full DEBOUNCE.
+          while let Ok(Some(path)) = tokio::time::timeout(DEBOUNCE, rx.recv()).await {
+            pending.insert(path);
+          }
+
+          for path in pending.drain() {
+            if let Err(err) = process_import(&config, &pool, &path).await {
+              error!("Failed to import {path:?}: {err:?}");
+            }
+          }
+        },
+        _ = sweep.tick() => {
+          info!("Running periodic import sweep...");
+          match check_imports(&config, &pool).await {
+            Ok(_) => info!("Periodic import sweep complete"),
+            Err(err) => error!("Periodic import sweep failed: {err:?}"),
+          }
+        },
+      }
+    }
+
+    let _ = watcher; // keep the watcher alive for the lifetime of this task
+  });
+}
+
+fn start_watcher(config: &Config, tx: mpsc::UnboundedSender<PathBuf>) -> Result<RecommendedWatcher> {
+  let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| match res {
+    Ok(event) => {
+      if !matches!(
+        event.kind,
+        EventKind::Create(CreateKind::File | CreateKind::Any)
+          | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Any)
+      ) {
+        return;
+      }
+
+      for path in event.paths {
+        let _ = tx.send(path);
+      }
+    },
+    Err(err) => warn!("Filesystem watch error: {err:?}"),
+  })?;
+
+  watcher.watch(&config.imports_path, RecursiveMode::Recursive)?;
+  Ok(watcher)
+}